@@ -1,12 +1,14 @@
 use std::f64::consts::PI;
-use std::thread::sleep;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
 use eframe::egui::{self, layers::ShapeIdx};
 use eframe::epaint::{CircleShape, Color32, Pos2, Rect, RectShape, Rounding, Shape, Stroke, Vec2};
 use eframe::NativeOptions;
 use rand::rngs::ThreadRng;
 use rand::Rng;
+use rhai::{Engine, Map as RhaiMap, Scope, AST};
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
 
 // const DEFAULT_SCREEN_WIDTH: f32 = 1920.;
 // const DEFAULT_SCREEN_HEIGHT: f32 = 1080.;
@@ -24,9 +26,36 @@ const CIRCLES_NUMBER: u32 = 750;
 const CIRCLES_MIN_RADIUS: f32 = 5.;
 const CIRCLES_MAX_RADIUS: f32 = 15.;
 const GRAVITY: Vec2 = Vec2 { x: 0., y: 0.1 };
-const SLEEPING_FRAME_MS: u64 = 1;
-const MAX_FPS: i32 = 144;
 const SUB_STEPS: i32 = 10;
+const CONSTRAINT_RADIUS: f32 = 300.;
+
+// Fixed physics timestep: `update_entities` always advances the simulation by
+// this much wall-clock time, no matter how fast or slow frames render.
+const DT: f32 = 1. / 120.;
+// Caps the catch-up work after a stall (e.g. the window was backgrounded) so
+// the accumulator can't spiral into simulating forever instead of rendering.
+const MAX_STEPS_PER_FRAME: u32 = 5;
+const SCENE_FILE: &str = "scene.toml";
+
+const GRAB_RADIUS: f32 = 40.;
+const ATTRACTION_RADIUS: f32 = 150.;
+const ATTRACTION_STRENGTH: f32 = 2.;
+
+const FORCE_SCRIPT_FILE: &str = "force_field.rhai";
+const SPAWN_SCRIPT_FILE: &str = "spawn_rule.rhai";
+
+// Cell size is chosen so two circles can never overlap across more than one
+// neighboring cell, which is what lets the half-neighbor scan below stay correct.
+const GRID_CELL_SIZE: f32 = 2. * CIRCLES_MAX_RADIUS;
+// Only the "forward" half of the 8-neighborhood is visited per cell; together
+// with the current cell this covers every unordered cell pair exactly once.
+const GRID_NEIGHBOR_OFFSETS: [(i32, i32); 4] = [(1, 0), (0, 1), (1, 1), (-1, 1)];
+
+#[derive(Clone, Copy)]
+enum ShapeKind {
+    Circle { radius: f32 },
+    Aabb { half_extents: Vec2 },
+}
 
 #[derive(Clone, Copy)]
 struct Entity {
@@ -36,7 +65,7 @@ struct Entity {
     old_position: Pos2,
     acceleration: Vec2,
     color: Color32,
-    radius: f32,
+    kind: ShapeKind,
 }
 
 impl PartialEq for Entity {
@@ -48,119 +77,592 @@ impl PartialEq for Entity {
 impl Eq for Entity {}
 
 impl Entity {
-    fn update(&mut self) {
+    fn update(&mut self, gravity: Vec2) {
         let velocity = self.position - self.old_position;
         self.old_position = self.position;
-        self.apply_gravity();
+        self.apply_gravity(gravity);
         self.position = self.position + velocity + self.acceleration;
         self.acceleration = Vec2::new(0., 0.);
     }
 
-    fn apply_gravity(&mut self) {
-        self.accelerate(GRAVITY);
+    fn apply_gravity(&mut self, gravity: Vec2) {
+        self.accelerate(gravity);
     }
 
     fn accelerate(&mut self, acc: Vec2) {
         self.acceleration += acc;
     }
 
+    fn bounding_radius(&self) -> f32 {
+        match self.kind {
+            ShapeKind::Circle { radius } => radius,
+            ShapeKind::Aabb { half_extents } => {
+                (half_extents.x.powi(2) + half_extents.y.powi(2)).sqrt()
+            }
+        }
+    }
+
+    fn extent_x(&self) -> f32 {
+        match self.kind {
+            ShapeKind::Circle { radius } => radius,
+            ShapeKind::Aabb { half_extents } => half_extents.x,
+        }
+    }
+
+    fn extent_y(&self) -> f32 {
+        match self.kind {
+            ShapeKind::Circle { radius } => radius,
+            ShapeKind::Aabb { half_extents } => half_extents.y,
+        }
+    }
+
     fn solve_collision(&mut self, other: &mut Self) {
+        match (self.kind, other.kind) {
+            (ShapeKind::Circle { radius: r1 }, ShapeKind::Circle { radius: r2 }) => {
+                Self::solve_circle_circle(self, other, r1, r2);
+            }
+            (ShapeKind::Aabb { half_extents: ha }, ShapeKind::Aabb { half_extents: hb }) => {
+                Self::solve_box_box(self, other, ha, hb);
+            }
+            (ShapeKind::Circle { radius }, ShapeKind::Aabb { half_extents }) => {
+                Self::solve_circle_box(self, radius, other, half_extents);
+            }
+            (ShapeKind::Aabb { half_extents }, ShapeKind::Circle { radius }) => {
+                Self::solve_circle_box(other, radius, self, half_extents);
+            }
+        }
+    }
+
+    fn solve_circle_circle(circle_a: &mut Self, circle_b: &mut Self, r1: f32, r2: f32) {
         let response_coef: f32 = 0.75;
-        let dist_pos = self.position - other.position;
+        let dist_pos = circle_a.position - circle_b.position;
         let dist2 = dist_pos.x.powi(2) + dist_pos.y.powi(2);
-        let min_dist = self.radius + other.radius;
+        let min_dist = r1 + r2;
 
         if dist2 < min_dist.powi(2) {
             let dist = f32::sqrt(dist2);
             let n = dist_pos / dist;
-            let mass_ratio_1 = self.radius / (self.radius + other.radius);
-            let mass_ratio_2 = other.radius / (self.radius + other.radius);
+            let mass_ratio_1 = r1 / (r1 + r2);
+            let mass_ratio_2 = r2 / (r1 + r2);
             let delta = 0.5 * response_coef * (dist - min_dist);
 
-            // self.old_position = self.position;
-            // other.old_position = other.position;
+            // circle_a.old_position = circle_a.position;
+            // circle_b.old_position = circle_b.position;
+
+            circle_a.position -= n * (mass_ratio_2 * delta);
+            circle_b.position += n * (mass_ratio_1 * delta);
+        }
+    }
+
+    fn solve_box_box(box_a: &mut Self, box_b: &mut Self, half_a: Vec2, half_b: Vec2) {
+        let response_coef: f32 = 0.75;
+        let delta = box_a.position - box_b.position;
+        let overlap_x = half_a.x + half_b.x - delta.x.abs();
+        let overlap_y = half_a.y + half_b.y - delta.y.abs();
+
+        if overlap_x <= 0. || overlap_y <= 0. {
+            return;
+        }
+
+        // Push apart along whichever axis is penetrating the least (minimum
+        // translation vector), so corner contacts resolve along one axis only.
+        if overlap_x < overlap_y {
+            let push = 0.5 * response_coef * overlap_x * delta.x.signum();
+            box_a.position.x += push;
+            box_b.position.x -= push;
+        } else {
+            let push = 0.5 * response_coef * overlap_y * delta.y.signum();
+            box_a.position.y += push;
+            box_b.position.y -= push;
+        }
+    }
 
-            self.position -= n * (mass_ratio_2 * delta);
-            other.position += n * (mass_ratio_1 * delta);
+    fn solve_circle_box(circle: &mut Self, radius: f32, aabb: &mut Self, half_extents: Vec2) {
+        let response_coef: f32 = 0.75;
+        let delta = circle.position - aabb.position;
+        let closest = Pos2 {
+            x: aabb.position.x + delta.x.clamp(-half_extents.x, half_extents.x),
+            y: aabb.position.y + delta.y.clamp(-half_extents.y, half_extents.y),
+        };
+        let diff = circle.position - closest;
+        let dist2 = diff.x.powi(2) + diff.y.powi(2);
+
+        if dist2 < radius.powi(2) && dist2 > f32::EPSILON {
+            let dist = dist2.sqrt();
+            let n = diff / dist;
+            let push = 0.5 * response_coef * (radius - dist);
+
+            circle.position += n * push;
+            aabb.position -= n * push;
         }
     }
 
-    fn apply_circle_contraint(&mut self) {
+    fn apply_circle_contraint(&mut self, canvas_radius: f32) {
         let constraint_center = Pos2 {
             x: (RECT_CANVAS_START.x + RECT_CANVAS_END.x) / 2.,
             y: (RECT_CANVAS_START.y + RECT_CANVAS_END.y) / 2.,
         };
         let v = constraint_center - self.position;
         let dist = f32::sqrt(v.x * v.x + v.y * v.y);
-        let canvas_radius = 300.;
-        if dist > (canvas_radius - self.radius) {
+        let bounding_radius = self.bounding_radius();
+        if dist > (canvas_radius - bounding_radius) {
             let n = v / dist;
-            self.position = constraint_center - n * (canvas_radius - self.radius);
+            self.position = constraint_center - n * (canvas_radius - bounding_radius);
         }
     }
 
     fn apply_contraint(&mut self) {
+        let extent_x = self.extent_x();
+        let extent_y = self.extent_y();
+
         // down
-        if self.position.y + self.radius > RECT_CANVAS_END.y {
+        if self.position.y + extent_y > RECT_CANVAS_END.y {
             self.old_position = self.position;
             self.position = Pos2 {
                 x: self.position.x,
-                y: (self.position.y - self.radius) - (self.position.y - RECT_CANVAS_END.y),
+                y: (self.position.y - extent_y) - (self.position.y - RECT_CANVAS_END.y),
             };
         }
 
         // up
-        if self.position.y - self.radius < RECT_CANVAS_START.y {
+        if self.position.y - extent_y < RECT_CANVAS_START.y {
             self.old_position = self.position;
             self.position = Pos2 {
                 x: self.position.x,
-                y: (self.position.y + self.radius) + (RECT_CANVAS_START.y - self.position.y),
+                y: (self.position.y + extent_y) + (RECT_CANVAS_START.y - self.position.y),
             };
         }
 
         // right
-        if self.position.x + self.radius > RECT_CANVAS_END.x {
+        if self.position.x + extent_x > RECT_CANVAS_END.x {
             self.old_position = self.position;
             self.position = Pos2 {
-                x: (self.position.x - self.radius) - (self.position.x - RECT_CANVAS_END.x),
+                x: (self.position.x - extent_x) - (self.position.x - RECT_CANVAS_END.x),
                 y: self.position.y,
             };
         }
 
         // left
-        if self.position.x - self.radius < RECT_CANVAS_START.x {
+        if self.position.x - extent_x < RECT_CANVAS_START.x {
             self.old_position = self.position;
             self.position = Pos2 {
-                x: (self.position.x + self.radius) + (RECT_CANVAS_START.x - self.position.x),
+                x: (self.position.x + extent_x) + (RECT_CANVAS_START.x - self.position.x),
                 y: self.position.y,
             };
         }
     }
 }
 
+// Plain, serde-friendly mirror of `ShapeKind` (which holds egui's non-serde
+// `Vec2`) so a scene can be written out as TOML.
+#[derive(Serialize, Deserialize)]
+enum SceneShapeKind {
+    Circle { radius: f32 },
+    Aabb { half_extents: (f32, f32) },
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntityState {
+    position: (f32, f32),
+    old_position: (f32, f32),
+    acceleration: (f32, f32),
+    color: (u8, u8, u8),
+    kind: SceneShapeKind,
+}
+
+// Snapshot of everything needed to resume a simulation later. `shape_id`
+// handles are deliberately excluded: they're egui painter handles and are
+// rebuilt from scratch when a scene is loaded.
+#[derive(Serialize, Deserialize)]
+struct SceneState {
+    gravity: (f32, f32),
+    sub_steps: i32,
+    constraint_radius: f32,
+    entities: Vec<EntityState>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Constraint {
+    Circle,
+    Rect,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RunState {
+    Running,
+    Paused,
+    Reset,
+}
+
+struct AppBuilder {
+    gravity: Vec2,
+    capacity: u32,
+    sub_steps: i32,
+    constraint: Constraint,
+}
+
+impl Default for AppBuilder {
+    fn default() -> Self {
+        Self {
+            gravity: GRAVITY,
+            capacity: CIRCLES_NUMBER,
+            sub_steps: SUB_STEPS,
+            constraint: Constraint::Circle,
+        }
+    }
+}
+
+impl AppBuilder {
+    fn with_gravity(mut self, gravity: Vec2) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    fn with_capacity(mut self, capacity: u32) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    fn with_substeps(mut self, sub_steps: i32) -> Self {
+        self.sub_steps = sub_steps;
+        self
+    }
+
+    fn with_constraint(mut self, constraint: Constraint) -> Self {
+        self.constraint = constraint;
+        self
+    }
+
+    fn build(self) -> App {
+        App::from_builder(self)
+    }
+}
+
 struct App {
     thread_rng: ThreadRng,
     next_entity_id: u64,
     entities: Vec<Entity>,
-    pub map: Vec<Vec<u32>>,
+    pub map: Vec<SmallVec<[u32; 4]>>,
     pub map_size: Vec2,
+    grid_cols: usize,
+    grid_rows: usize,
+    gravity: Vec2,
+    capacity: u32,
+    sub_steps: i32,
+    constraint: Constraint,
+    constraint_radius: f32,
+    dragged_entity: Option<u64>,
+    script_engine: Engine,
+    force_script: Option<AST>,
+    spawn_script: Option<AST>,
+    sim_time: f32,
+    run_state: RunState,
 }
 
 impl App {
-    fn new() -> Self {
+    fn from_builder(builder: AppBuilder) -> Self {
+        let map_size = RECT_CANVAS_END - RECT_CANVAS_START;
+        let grid_cols = (map_size.x / GRID_CELL_SIZE).ceil() as usize;
+        let grid_rows = (map_size.y / GRID_CELL_SIZE).ceil() as usize;
+
         Self {
             thread_rng: rand::thread_rng(),
             next_entity_id: 1,
             entities: Vec::new(),
-            map_size: MAP_SIZE,
-            map: (0..MAP_SIZE.x as _)
-                .map(|_| (0..MAP_SIZE.y as _).collect())
+            map_size,
+            grid_cols,
+            grid_rows,
+            map: vec![SmallVec::new(); grid_cols * grid_rows],
+            gravity: builder.gravity,
+            capacity: builder.capacity,
+            sub_steps: builder.sub_steps,
+            constraint: builder.constraint,
+            constraint_radius: CONSTRAINT_RADIUS,
+            dragged_entity: None,
+            script_engine: Self::build_script_engine(),
+            force_script: None,
+            spawn_script: None,
+            sim_time: 0.,
+            run_state: RunState::Running,
+        }
+    }
+
+    // `Vec2`/`Pos2` come from epaint and have no Rhai bindings out of the box,
+    // so force/spawn scripts get a `vec2(x, y)` constructor plus `.x`/`.y`
+    // accessors on both types.
+    fn build_script_engine() -> Engine {
+        let mut engine = Engine::new();
+
+        engine
+            .register_type_with_name::<Vec2>("Vec2")
+            .register_fn("vec2", |x: f64, y: f64| Vec2::new(x as f32, y as f32))
+            .register_get("x", |v: &mut Vec2| v.x as f64)
+            .register_get("y", |v: &mut Vec2| v.y as f64);
+
+        engine
+            .register_type_with_name::<Pos2>("Pos2")
+            .register_get("x", |p: &mut Pos2| p.x as f64)
+            .register_get("y", |p: &mut Pos2| p.y as f64);
+
+        engine
+    }
+
+    fn reload_force_script(&mut self) {
+        self.force_script = std::fs::read_to_string(FORCE_SCRIPT_FILE)
+            .ok()
+            .and_then(|src| self.script_engine.compile(src).ok());
+    }
+
+    fn reload_spawn_script(&mut self) {
+        self.spawn_script = std::fs::read_to_string(SPAWN_SCRIPT_FILE)
+            .ok()
+            .and_then(|src| self.script_engine.compile(src).ok());
+    }
+
+    // Adds a per-entity acceleration returned by the `force` function of the
+    // loaded script on top of the usual gravity. Any compile/eval failure -
+    // including no script being loaded at all - leaves entities under plain
+    // `GRAVITY`, so a typo in the script can't crash the sim.
+    fn apply_force_script(&mut self) {
+        let ast = match &self.force_script {
+            Some(ast) => ast.clone(),
+            None => return,
+        };
+
+        let time = self.sim_time as f64;
+
+        for entity in self.entities.iter_mut() {
+            let velocity = entity.position - entity.old_position;
+            let mut scope = Scope::new();
+            let result: Result<Vec2, _> = self.script_engine.call_fn(
+                &mut scope,
+                &ast,
+                "force",
+                (entity.position, velocity, time),
+            );
+
+            if let Ok(extra) = result {
+                entity.accelerate(extra);
+            }
+        }
+    }
+
+    // Lets a `spawn` script pick position/radius/color instead of the
+    // hardcoded column-drop logic in `create_circles`. Returns `false` (so the
+    // caller falls back to the built-in spawn) if no script is loaded or it
+    // errors.
+    fn spawn_circle_via_script(&mut self, ui: &mut egui::Ui) -> bool {
+        let ast = match &self.spawn_script {
+            Some(ast) => ast.clone(),
+            None => return false,
+        };
+
+        let mut scope = Scope::new();
+        let result: Result<RhaiMap, _> = self.script_engine.call_fn(
+            &mut scope,
+            &ast,
+            "spawn",
+            (self.next_entity_id as i64, self.sim_time as f64),
+        );
+
+        let values = match result {
+            Ok(values) => values,
+            Err(_) => return false,
+        };
+
+        let position = Pos2::new(
+            Self::map_f64(&values, "x", CIRCLE_STARTING_POS.x as f64) as f32,
+            Self::map_f64(&values, "y", CIRCLE_STARTING_POS.y as f64) as f32,
+        );
+        let radius = Self::map_f64(&values, "radius", CIRCLES_MAX_RADIUS as f64) as f32;
+        let color = Color32::from_rgb(
+            Self::map_i64(&values, "r", 255) as u8,
+            Self::map_i64(&values, "g", 255) as u8,
+            Self::map_i64(&values, "b", 255) as u8,
+        );
+
+        self.entities.push(Entity {
+            id: self.next_entity_id,
+            shape_id: ui.painter().add(Shape::Circle(CircleShape {
+                center: position,
+                radius,
+                fill: color,
+                stroke: Stroke {
+                    width: 0.,
+                    color: Color32::WHITE,
+                },
+            })),
+            position,
+            old_position: position,
+            acceleration: Vec2::default(),
+            kind: ShapeKind::Circle { radius },
+            color,
+        });
+
+        self.next_entity_id += 1;
+
+        true
+    }
+
+    fn map_f64(map: &RhaiMap, key: &str, default: f64) -> f64 {
+        map.get(key).and_then(|v| v.as_float().ok()).unwrap_or(default)
+    }
+
+    fn map_i64(map: &RhaiMap, key: &str, default: i64) -> i64 {
+        map.get(key).and_then(|v| v.as_int().ok()).unwrap_or(default)
+    }
+
+    fn snapshot(&self) -> SceneState {
+        SceneState {
+            gravity: (self.gravity.x, self.gravity.y),
+            sub_steps: self.sub_steps,
+            constraint_radius: self.constraint_radius,
+            entities: self
+                .entities
+                .iter()
+                .map(|e| EntityState {
+                    position: (e.position.x, e.position.y),
+                    old_position: (e.old_position.x, e.old_position.y),
+                    acceleration: (e.acceleration.x, e.acceleration.y),
+                    color: (e.color.r(), e.color.g(), e.color.b()),
+                    kind: match e.kind {
+                        ShapeKind::Circle { radius } => SceneShapeKind::Circle { radius },
+                        ShapeKind::Aabb { half_extents } => SceneShapeKind::Aabb {
+                            half_extents: (half_extents.x, half_extents.y),
+                        },
+                    },
+                })
                 .collect(),
         }
     }
 
+    fn save_scene(&self, path: &str) -> std::io::Result<()> {
+        let scene = self.snapshot();
+        let serialized = toml::to_string_pretty(&scene).expect("scene state should serialize");
+        std::fs::write(path, serialized)
+    }
+
+    fn load_scene(&mut self, ui: &mut egui::Ui, path: &str) -> std::io::Result<()> {
+        let contents = std::fs::read_to_string(path)?;
+        let scene: SceneState = toml::from_str(&contents)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        self.entities.clear();
+        self.next_entity_id = 1;
+        self.gravity = Vec2::new(scene.gravity.0, scene.gravity.1);
+        self.sub_steps = scene.sub_steps;
+        self.constraint_radius = scene.constraint_radius;
+
+        for state in scene.entities {
+            let position = Pos2::new(state.position.0, state.position.1);
+            let color = Color32::from_rgb(state.color.0, state.color.1, state.color.2);
+            let kind = match state.kind {
+                SceneShapeKind::Circle { radius } => ShapeKind::Circle { radius },
+                SceneShapeKind::Aabb { half_extents } => ShapeKind::Aabb {
+                    half_extents: Vec2::new(half_extents.0, half_extents.1),
+                },
+            };
+            let shape_id = match kind {
+                ShapeKind::Circle { radius } => ui.painter().add(Shape::Circle(CircleShape {
+                    center: position,
+                    radius,
+                    fill: color,
+                    stroke: Stroke {
+                        width: 0.,
+                        color: Color32::WHITE,
+                    },
+                })),
+                ShapeKind::Aabb { half_extents } => ui.painter().add(Shape::Rect(RectShape {
+                    rect: Rect::from_center_size(position, half_extents * 2.),
+                    rounding: Rounding::none(),
+                    fill: color,
+                    stroke: Stroke {
+                        width: 0.,
+                        color,
+                    },
+                })),
+            };
+
+            self.entities.push(Entity {
+                id: self.next_entity_id,
+                shape_id,
+                position,
+                old_position: Pos2::new(state.old_position.0, state.old_position.1),
+                acceleration: Vec2::new(state.acceleration.0, state.acceleration.1),
+                color,
+                kind,
+            });
+            self.next_entity_id += 1;
+        }
+
+        Ok(())
+    }
+
+    // Drops every entity and goes back to `Running`, keeping the builder
+    // config (gravity, capacity, sub-steps, constraint) intact.
+    fn reset(&mut self) {
+        self.entities.clear();
+        self.next_entity_id = 1;
+        self.sim_time = 0.;
+        self.dragged_entity = None;
+
+        for cell in self.map.iter_mut() {
+            cell.clear();
+        }
+
+        self.run_state = RunState::Running;
+    }
+
+    fn cell_coords(&self, position: Pos2) -> (usize, usize) {
+        let local = position - RECT_CANVAS_START;
+        let cx = (local.x / GRID_CELL_SIZE).floor() as i32;
+        let cy = (local.y / GRID_CELL_SIZE).floor() as i32;
+
+        (
+            cx.clamp(0, self.grid_cols as i32 - 1) as usize,
+            cy.clamp(0, self.grid_rows as i32 - 1) as usize,
+        )
+    }
+
+    fn rebuild_grid(&mut self) {
+        for cell in self.map.iter_mut() {
+            cell.clear();
+        }
+
+        for (i, entity) in self.entities.iter().enumerate() {
+            let (cx, cy) = self.cell_coords(entity.position);
+            self.map[cy * self.grid_cols + cx].push(i as u32);
+        }
+    }
+
+    fn solve_pair(entities: &mut [Entity], i: u32, j: u32) {
+        let (lo, hi) = if i < j { (i, j) } else { (j, i) };
+        let (left, right) = entities.split_at_mut(hi as usize);
+        left[lo as usize].solve_collision(&mut right[0]);
+    }
+
+    fn solve_own_cell(entities: &mut [Entity], members: &[u32]) {
+        for (a, &i) in members.iter().enumerate() {
+            for &j in &members[a + 1..] {
+                Self::solve_pair(entities, i, j);
+            }
+        }
+    }
+
+    fn solve_neighbor_cell(entities: &mut [Entity], a: &[u32], b: &[u32]) {
+        for &i in a {
+            for &j in b {
+                Self::solve_pair(entities, i, j);
+            }
+        }
+    }
+
     fn create_circles(&mut self, ui: &mut egui::Ui) {
-        if self.entities.len() == CIRCLES_NUMBER as usize {
+        if self.entities.len() == self.capacity as usize {
+            return;
+        }
+
+        if self.spawn_circle_via_script(ui) {
             return;
         }
 
@@ -168,7 +670,11 @@ impl App {
             x: CIRCLE_STARTING_POS.x + self.next_entity_id as f32 * 5. % 500.,
             ..CIRCLE_STARTING_POS
         };
-        // let radius = CIRCLES_MAX_RADIUS;
+
+        self.spawn_circle_at(ui, position);
+    }
+
+    fn spawn_circle_at(&mut self, ui: &mut egui::Ui, position: Pos2) {
         let radius = self
             .thread_rng
             .gen_range(CIRCLES_MIN_RADIUS..=CIRCLES_MAX_RADIUS);
@@ -188,27 +694,104 @@ impl App {
             position,
             old_position: position,
             acceleration: Vec2::default(),
-            radius,
+            kind: ShapeKind::Circle { radius },
             color,
         });
 
         self.next_entity_id += 1;
     }
 
+    fn nearest_entity_within(&self, position: Pos2, max_dist: f32) -> Option<u64> {
+        self.entities
+            .iter()
+            .map(|e| (e.id, (e.position - position).length()))
+            .filter(|(_, dist)| *dist <= max_dist)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .map(|(id, _)| id)
+    }
+
+    fn handle_pointer_input(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let pointer_pos = ctx.input(|i| i.pointer.hover_pos());
+        let mut just_spawned = false;
+
+        if ctx.input(|i| i.pointer.primary_clicked()) {
+            if let Some(pos) = pointer_pos {
+                if self.nearest_entity_within(pos, GRAB_RADIUS).is_none() {
+                    self.spawn_circle_at(ui, pos);
+                    just_spawned = true;
+                }
+            }
+        }
+
+        if ctx.input(|i| i.pointer.primary_down()) {
+            if let Some(pos) = pointer_pos {
+                // A body spawned this very press sits exactly under the
+                // cursor, so it would otherwise immediately satisfy the
+                // nearest-entity grab check below and start a drag.
+                if self.dragged_entity.is_none() && !just_spawned {
+                    self.dragged_entity = self.nearest_entity_within(pos, GRAB_RADIUS);
+                }
+
+                if let Some(entity) = self
+                    .dragged_entity
+                    .and_then(|id| self.entities.iter_mut().find(|e| e.id == id))
+                {
+                    entity.old_position = entity.position;
+                    entity.position = pos;
+                }
+            }
+        } else {
+            self.dragged_entity = None;
+        }
+
+        if let Some(pos) = pointer_pos.filter(|_| ctx.input(|i| i.pointer.secondary_down())) {
+            let sign = if ctx.input(|i| i.modifiers.alt) { -1. } else { 1. };
+
+            for entity in self.entities.iter_mut() {
+                if Some(entity.id) == self.dragged_entity {
+                    continue;
+                }
+
+                let to_pointer = pos - entity.position;
+                let dist = to_pointer.length().max(1.);
+
+                if dist <= ATTRACTION_RADIUS {
+                    let n = to_pointer / dist;
+                    entity.accelerate(n * (sign * ATTRACTION_STRENGTH / dist));
+                }
+            }
+        }
+    }
+
     fn draw_cricles(&self, ui: &mut egui::Ui) {
         for e in self.entities.iter() {
-            ui.painter().add(Shape::Circle(CircleShape {
-                center: Pos2 {
-                    x: e.position.x,
-                    y: e.position.y,
-                },
-                radius: e.radius,
-                fill: e.color,
-                stroke: Stroke {
-                    width: 0.,
-                    color: e.color,
-                },
-            }));
+            match e.kind {
+                ShapeKind::Circle { radius } => {
+                    ui.painter().add(Shape::Circle(CircleShape {
+                        center: Pos2 {
+                            x: e.position.x,
+                            y: e.position.y,
+                        },
+                        radius,
+                        fill: e.color,
+                        stroke: Stroke {
+                            width: 0.,
+                            color: e.color,
+                        },
+                    }));
+                }
+                ShapeKind::Aabb { half_extents } => {
+                    ui.painter().add(Shape::Rect(RectShape {
+                        rect: Rect::from_center_size(e.position, half_extents * 2.),
+                        rounding: Rounding::none(),
+                        fill: e.color,
+                        stroke: Stroke {
+                            width: 0.,
+                            color: e.color,
+                        },
+                    }));
+                }
+            }
         }
     }
 
@@ -228,17 +811,45 @@ impl App {
     }
 
     fn update_entities(&mut self) {
-        for i in 0..self.entities.len() {
-            let (entity, entities) = self.entities[i..].split_first_mut().unwrap();
-            for entity2 in entities {
-                entity.solve_collision(entity2);
+        // `update_entities` runs `sub_steps` times per drained `DT`, so each
+        // call only accounts for its share of that tick - otherwise scripted
+        // time-varying forces would run `sub_steps`x too fast.
+        self.sim_time += DT / self.sub_steps.max(1) as f32;
+        self.apply_force_script();
+        self.rebuild_grid();
+
+        for cy in 0..self.grid_rows {
+            for cx in 0..self.grid_cols {
+                let own = self.map[cy * self.grid_cols + cx].clone();
+                Self::solve_own_cell(&mut self.entities, &own);
+
+                for (dx, dy) in GRID_NEIGHBOR_OFFSETS {
+                    let nx = cx as i32 + dx;
+                    let ny = cy as i32 + dy;
+
+                    if nx < 0 || ny < 0 || nx >= self.grid_cols as i32 || ny >= self.grid_rows as i32 {
+                        continue;
+                    }
+
+                    let neighbor = self.map[ny as usize * self.grid_cols + nx as usize].clone();
+                    Self::solve_neighbor_cell(&mut self.entities, &own, &neighbor);
+                }
             }
+        }
 
-            let entity = self.entities.get_mut(i).unwrap();
+        for entity in self.entities.iter_mut() {
+            match self.constraint {
+                Constraint::Circle => entity.apply_circle_contraint(self.constraint_radius),
+                Constraint::Rect => entity.apply_contraint(),
+            }
 
-            entity.apply_circle_contraint();
-            // entity.apply_contraint();
-            entity.update();
+            // A dragged entity is pinned to the pointer in `handle_pointer_input`
+            // instead; skipping the Verlet integration here keeps it glued to the
+            // cursor without gravity fighting the drag, while still letting it
+            // push other entities through the collision pass above.
+            if Some(entity.id) != self.dragged_entity {
+                entity.update(self.gravity);
+            }
         }
     }
 
@@ -260,6 +871,8 @@ struct Window {
     app: App,
     frame_time: Instant,
     frames: u64,
+    last_instant: Instant,
+    accumulator: f32,
 }
 
 impl Window {
@@ -268,6 +881,8 @@ impl Window {
             app,
             frames: 0,
             frame_time: Instant::now(),
+            last_instant: Instant::now(),
+            accumulator: 0.,
         }
     }
 }
@@ -278,33 +893,96 @@ impl eframe::App for Window {
         let fps = self.frames / if elapsed == 0 { 1 } else { elapsed };
         self.frames += 1;
 
+        if ctx.input(|i| i.key_pressed(egui::Key::Space)) {
+            self.app.run_state = match self.app.run_state {
+                RunState::Running => RunState::Paused,
+                RunState::Paused | RunState::Reset => RunState::Running,
+            };
+        }
+
+        if self.app.run_state == RunState::Reset {
+            self.app.reset();
+        }
+
         egui::TopBottomPanel::top("app state").show(ctx, |ui| {
-            ui.label(
-                egui::RichText::new(format!("{} entities", self.app.entities.len()))
-                    .color(Color32::WHITE)
-                    .size(12.)
-                    .strong(),
-            );
-            ui.label(
-                egui::RichText::new(format!("{fps} FPS"))
-                    .color(Color32::WHITE)
-                    .size(12.)
-                    .strong(),
-            );
+            ui.horizontal(|ui| {
+                ui.label(
+                    egui::RichText::new(format!("{} entities", self.app.entities.len()))
+                        .color(Color32::WHITE)
+                        .size(12.)
+                        .strong(),
+                );
+                ui.label(
+                    egui::RichText::new(format!("{fps} FPS"))
+                        .color(Color32::WHITE)
+                        .size(12.)
+                        .strong(),
+                );
+
+                let pause_label = match self.app.run_state {
+                    RunState::Paused => "Resume",
+                    _ => "Pause",
+                };
+                if ui.button(pause_label).clicked() {
+                    self.app.run_state = match self.app.run_state {
+                        RunState::Paused => RunState::Running,
+                        _ => RunState::Paused,
+                    };
+                }
+
+                if ui.button("Reset").clicked() {
+                    self.app.run_state = RunState::Reset;
+                }
+            });
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::Frame::dark_canvas(ui.style()).show(ui, |ui| {
                 self.app.draw_rect_canvas(ui);
 
-                if self.frames % 4 == 0 {
+                if self.app.run_state == RunState::Running && self.frames % 4 == 0 {
                     self.app.create_circles(ui);
                 }
 
                 self.app.draw_cricles(ui);
 
-                for _ in 0..2 {
-                    self.app.update_entities();
+                self.app.handle_pointer_input(ctx, ui);
+
+                if self.app.run_state == RunState::Running {
+                    let now = Instant::now();
+                    self.accumulator += (now - self.last_instant).as_secs_f32();
+                    self.last_instant = now;
+
+                    let mut steps_run = 0;
+                    while self.accumulator >= DT && steps_run < MAX_STEPS_PER_FRAME {
+                        for _ in 0..self.app.sub_steps {
+                            self.app.update_entities();
+                        }
+                        self.accumulator -= DT;
+                        steps_run += 1;
+                    }
+                } else {
+                    self.last_instant = Instant::now();
+                }
+
+                if ctx.input(|i| i.key_pressed(egui::Key::F5)) {
+                    if let Err(e) = self.app.save_scene(SCENE_FILE) {
+                        eprintln!("failed to save scene to {SCENE_FILE}: {e}");
+                    }
+                }
+
+                if ctx.input(|i| i.key_pressed(egui::Key::F9)) {
+                    if let Err(e) = self.app.load_scene(ui, SCENE_FILE) {
+                        eprintln!("failed to load scene from {SCENE_FILE}: {e}");
+                    }
+                }
+
+                if ctx.input(|i| i.key_pressed(egui::Key::F6)) {
+                    self.app.reload_force_script();
+                }
+
+                if ctx.input(|i| i.key_pressed(egui::Key::F7)) {
+                    self.app.reload_spawn_script();
                 }
             });
         });
@@ -314,13 +992,17 @@ impl eframe::App for Window {
             std::process::exit(0);
         }
 
-        sleep(Duration::from_millis(SLEEPING_FRAME_MS));
         ctx.request_repaint();
     }
 }
 
 fn main() -> Result<(), eframe::Error> {
-    let app = App::new();
+    let app = AppBuilder::default()
+        .with_gravity(GRAVITY)
+        .with_capacity(CIRCLES_NUMBER)
+        .with_substeps(SUB_STEPS)
+        .with_constraint(Constraint::Circle)
+        .build();
 
     let native_options = NativeOptions {
         always_on_top: false,